@@ -47,16 +47,19 @@ struct Uniforms {
 struct ErrorCallback(Option<js_sys::Function>);
 
 impl ErrorCallback {
-    fn call(&self, summary: &str, row: usize, col: usize) {
+    // `file` is appended as a trailing argument so existing frontends reading
+    // `row`/`col` out of positions 2/3 keep working unchanged.
+    fn call(&self, summary: &str, row: usize, col: usize, file: &str) {
         match self.0 {
             None => log::error!("No error callback registered"),
             Some(ref callback) => {
-                let res = callback.call3(
-                    &JsValue::NULL,
+                let args = js_sys::Array::of4(
                     &JsValue::from(summary),
                     &JsValue::from(row),
-                    &JsValue::from(col)
+                    &JsValue::from(col),
+                    &JsValue::from(file),
                 );
+                let res = callback.apply(&JsValue::NULL, &args);
                 match res {
                     Err(error) => log::error!("Error calling registered error callback: {error:?}"),
                     _ => ()
@@ -87,13 +90,312 @@ impl SuccessCallback {
     }
 }
 
+#[derive(Clone)]
+struct TimingCallback(Option<js_sys::Function>);
+
+impl TimingCallback {
+    fn call(&self, timings: &[(String, f64)]) {
+        if let Some(ref callback) = self.0 {
+            let array = js_sys::Array::new();
+            for (name, ms) in timings {
+                let entry = js_sys::Object::new();
+                let _ = js_sys::Reflect::set(&entry, &JsValue::from("name"), &JsValue::from(name));
+                let _ = js_sys::Reflect::set(&entry, &JsValue::from("ms"), &JsValue::from(*ms));
+                array.push(&entry);
+            }
+            if let Err(error) = callback.call1(&JsValue::NULL, &array) {
+                log::error!("Error calling registered timing callback: {error:?}");
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AudioCallback(Option<js_sys::Function>);
+
+impl AudioCallback {
+    fn call(&self, samples: &[f32], sample_rate: f64) {
+        if let Some(ref callback) = self.0 {
+            let array = js_sys::Float32Array::from(samples);
+            if let Err(error) = callback.call2(&JsValue::NULL, &array, &JsValue::from(sample_rate)) {
+                log::error!("Error calling registered audio callback: {error:?}");
+            }
+        }
+    }
+}
+
 // safe because wasm is single-threaded: https://github.com/rustwasm/wasm-bindgen/issues/1505
 unsafe impl Send for ErrorCallback {}
 unsafe impl Sync for ErrorCallback {}
+unsafe impl Send for TimingCallback {}
+unsafe impl Sync for TimingCallback {}
+unsafe impl Send for AudioCallback {}
+unsafe impl Sync for AudioCallback {}
 
 const NUM_KEYCODES: usize = 256;
 const MAX_CUSTOM_PARAMS: usize = 16;
+const MAX_CUSTOM_BYTES: usize = MAX_CUSTOM_PARAMS * 16; // worst case: every param is a vec4
 const NUM_ASSERT_COUNTERS: usize = 10;
+// Weight given to the newest sample when smoothing per-pass GPU timings;
+// lower is smoother but slower to react to genuine cost changes.
+const TIMING_EMA_ALPHA: f64 = 0.1;
+// A `sound` entry point, if present, is dispatched once per frame over this
+// many stereo sample indices rather than over the screen.
+const SOUND_SAMPLES_PER_DISPATCH: u32 = 4096;
+const SOUND_SAMPLE_RATE: f64 = 44100.0;
+
+// WGSL type a custom parameter is exposed as in the generated `Custom` struct.
+// Values always arrive from JS as `f32` and are converted on pack.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CustomType {
+    Int,
+    UInt,
+    Float,
+    Bool,
+    Vec2,
+    Vec3,
+    Vec4,
+    // A vec3/vec4 fed by a color-picker widget; gamma-decoded to linear on pack.
+    ColorSrgb3,
+    ColorSrgb4,
+}
+
+impl CustomType {
+    fn from_tag(tag: u32) -> CustomType {
+        match tag {
+            1 => CustomType::Int,
+            2 => CustomType::UInt,
+            3 => CustomType::Bool,
+            4 => CustomType::Vec2,
+            5 => CustomType::Vec3,
+            6 => CustomType::Vec4,
+            7 => CustomType::ColorSrgb3,
+            8 => CustomType::ColorSrgb4,
+            _ => CustomType::Float,
+        }
+    }
+
+    fn components(self) -> usize {
+        match self {
+            CustomType::Vec2 => 2,
+            CustomType::Vec3 | CustomType::ColorSrgb3 => 3,
+            CustomType::Vec4 | CustomType::ColorSrgb4 => 4,
+            _ => 1,
+        }
+    }
+
+    // Mirrors WGSL's uniform address space layout rules (vec3 aligned/sized as vec4).
+    fn align(self) -> usize {
+        match self {
+            CustomType::Int | CustomType::UInt | CustomType::Float | CustomType::Bool => 4,
+            CustomType::Vec2 => 8,
+            CustomType::Vec3 | CustomType::Vec4 | CustomType::ColorSrgb3 | CustomType::ColorSrgb4 => 16,
+        }
+    }
+
+    fn wgsl_name(self) -> &'static str {
+        match self {
+            CustomType::Int => "int",
+            CustomType::UInt => "uint",
+            CustomType::Float => "float",
+            CustomType::Bool => "uint", // bool isn't host-shareable in WGSL; compare against 0u
+            CustomType::Vec2 => "float2",
+            CustomType::Vec3 | CustomType::ColorSrgb3 => "float3",
+            CustomType::Vec4 | CustomType::ColorSrgb4 => "float4",
+        }
+    }
+}
+
+// Standard sRGB EOTF: decodes a normalized 0..1 component into linear light.
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+struct CustomParam {
+    name: String,
+    ty: CustomType,
+    values: Vec<f32>,
+}
+
+// Packs custom params into uniform-buffer bytes; must stay in lockstep with
+// the `struct Custom { .. }` WGSL emitted by `prelude()`.
+fn pack_custom(params: &[CustomParam]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for p in params {
+        while bytes.len() % p.ty.align() != 0 {
+            bytes.push(0);
+        }
+        match p.ty {
+            CustomType::Int => bytes.extend_from_slice(&(p.values[0].round() as i32).to_le_bytes()),
+            CustomType::UInt | CustomType::Bool => bytes.extend_from_slice(&(p.values[0].round() as u32).to_le_bytes()),
+            CustomType::Float | CustomType::Vec2 | CustomType::Vec3 | CustomType::Vec4 => {
+                for v in &p.values {
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                if p.ty == CustomType::Vec3 {
+                    bytes.extend_from_slice(&[0u8; 4]); // pad vec3 up to vec4 size
+                }
+            }
+            CustomType::ColorSrgb3 | CustomType::ColorSrgb4 => {
+                // Only the RGB components go through the sRGB EOTF; alpha (if
+                // present) is already linear opacity.
+                for (i, v) in p.values.iter().enumerate() {
+                    let v = if i < 3 { srgb_to_linear(*v) } else { *v };
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                if p.ty == CustomType::ColorSrgb3 {
+                    bytes.extend_from_slice(&[0u8; 4]); // pad vec3 up to vec4 size
+                }
+            }
+        }
+    }
+    bytes
+}
+const NUM_TEX_LAYERS: u8 = 4;
+const ALL_LAYERS: u8 = (1 << NUM_TEX_LAYERS) - 1;
+
+// A single compute pass plus the layers of `pass_in`/`pass_out` it touches,
+// as bitmasks over the 4 array layers; narrowed from `ALL_LAYERS` by an
+// `// @layers(in: 0,1 out: 2)` directive above the entry point in WGSL.
+struct ComputePass {
+    pipeline: wgpu::ComputePipeline,
+    name: String,
+    workgroup_size: [u32; 3],
+    layers_read: u8,
+    layers_write: u8,
+}
+
+// Result of flattening `#include`s, `#define`s and `#ifdef` blocks into a
+// single buffer; `line_map[i]` gives the (file, line) that produced line `i`.
+struct Preprocessed {
+    source: String,
+    line_map: Vec<(String, usize)>,
+}
+
+// Splices `#include "name"`, substitutes `#define`s, and evaluates
+// `#ifdef`/`#ifndef`/`#else`/`#endif` blocks, ahead of `wgsl::parse_str`.
+fn preprocess(shader: &str, includes: &std::collections::HashMap<String, String>) -> Result<Preprocessed, String> {
+    let mut out = Preprocessed { source: String::new(), line_map: Vec::new() };
+    let mut defines = std::collections::HashMap::new();
+    let mut visiting = std::collections::HashSet::new();
+    preprocess_into("<shader>", shader, includes, &mut defines, &mut visiting, &mut out)?;
+    Ok(out)
+}
+
+struct CondFrame {
+    parent_active: bool,
+    taken: bool,
+    active: bool,
+}
+
+fn substitute_defines(line: &str, defines: &std::collections::HashMap<String, String>) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_ascii_alphabetic() || chars[i] == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            match defines.get(&ident) {
+                Some(replacement) => result.push_str(replacement),
+                None => result.push_str(&ident),
+            }
+        } else {
+            result.push(chars[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+fn preprocess_into(
+    file: &str,
+    src: &str,
+    includes: &std::collections::HashMap<String, String>,
+    defines: &mut std::collections::HashMap<String, String>,
+    visiting: &mut std::collections::HashSet<String>,
+    out: &mut Preprocessed,
+) -> Result<(), String> {
+    let mut conds: Vec<CondFrame> = Vec::new();
+    let is_active = |conds: &[CondFrame]| conds.last().map(|f| f.active).unwrap_or(true);
+
+    for (lineno, raw_line) in src.lines().enumerate() {
+        let line = raw_line.trim_start();
+        let active = is_active(&conds);
+        if let Some(name) = line.strip_prefix("#ifdef ") {
+            let cond = active && defines.contains_key(name.trim());
+            conds.push(CondFrame { parent_active: active, taken: cond, active: cond });
+        } else if let Some(name) = line.strip_prefix("#ifndef ") {
+            let cond = active && !defines.contains_key(name.trim());
+            conds.push(CondFrame { parent_active: active, taken: cond, active: cond });
+        } else if line.starts_with("#else") {
+            if let Some(frame) = conds.last_mut() {
+                frame.active = frame.parent_active && !frame.taken;
+                frame.taken = true;
+            } else {
+                return Err(format!("{file}:{}: #else without matching #ifdef/#ifndef", lineno + 1));
+            }
+        } else if line.starts_with("#endif") {
+            if conds.pop().is_none() {
+                return Err(format!("{file}:{}: #endif without matching #ifdef/#ifndef", lineno + 1));
+            }
+        } else if !active {
+            continue;
+        } else if let Some(rest) = line.strip_prefix("#define ") {
+            let mut parts = rest.trim().splitn(2, char::is_whitespace);
+            let name = parts.next().unwrap_or("").to_string();
+            let value = parts.next().unwrap_or("").trim().to_string();
+            defines.insert(name, value);
+        } else if let Some(rest) = line.strip_prefix("#include ") {
+            let name = rest.trim().trim_matches('"').to_string();
+            if visiting.contains(&name) {
+                return Err(format!("{file}:{}: include cycle detected on \"{name}\"", lineno + 1));
+            }
+            let include_src = includes.get(&name)
+                .ok_or_else(|| format!("{file}:{}: unknown include \"{name}\"", lineno + 1))?
+                .clone();
+            visiting.insert(name.clone());
+            preprocess_into(&name, &include_src, includes, defines, visiting, out)?;
+            visiting.remove(&name);
+        } else {
+            out.source.push_str(&substitute_defines(raw_line, defines));
+            out.source.push('\n');
+            out.line_map.push((file.to_string(), lineno + 1));
+        }
+    }
+    if !conds.is_empty() {
+        return Err(format!("{file}: unterminated #ifdef/#ifndef ({} still open)", conds.len()));
+    }
+    Ok(())
+}
+
+// Scans the raw shader text for `// @layers(in: .. out: ..)` directives and
+// associates each with the entry point declared immediately below it.
+fn parse_pass_layers(shader: &str) -> std::collections::HashMap<String, (u8, u8)> {
+    let directive = lazy_regex::regex!(r"@layers\(\s*in:\s*([0-9,\s]*)\s*out:\s*([0-9,\s]*)\s*\)");
+    let entry_fn = lazy_regex::regex!(r"^\s*fn\s+(\w+)\s*\(");
+    let mut result = std::collections::HashMap::new();
+    let mut pending: Option<(u8, u8)> = None;
+    for line in shader.lines() {
+        if let Some(cap) = directive.captures(line) {
+            let to_mask = |list: &str| list.split(',').filter_map(|s| s.trim().parse::<u8>().ok())
+                .fold(0u8, |mask, layer| mask | (1 << layer));
+            pending = Some((to_mask(&cap[1]), to_mask(&cap[2])));
+        } else if let Some(cap) = entry_fn.captures(line) {
+            if let Some(masks) = pending.take() {
+                result.insert(cap[1].to_string(), masks);
+            }
+        }
+    }
+    result
+}
 
 #[wasm_bindgen]
 pub struct WgpuToyRenderer {
@@ -103,26 +405,91 @@ pub struct WgpuToyRenderer {
     time: Time,
     mouse: Mouse,
     keys: BitArr!(for NUM_KEYCODES, in u8, Lsb0),
-    custom_names: Vec<String>,
-    custom_values: Vec<f32>,
+    custom_params: Vec<CustomParam>,
     uniforms: Uniforms,
     compute_bind_group_layout: wgpu::BindGroupLayout,
     compute_pipeline_layout: wgpu::PipelineLayout,
-    last_compute_pipelines: Option<Vec<(wgpu::ComputePipeline, [u32; 3])>>,
-    compute_pipelines: Vec<(wgpu::ComputePipeline, [u32; 3])>,
+    last_compute_pipelines: Option<Vec<ComputePass>>,
+    compute_pipelines: Vec<ComputePass>,
     compute_bind_group: wgpu::BindGroup,
     staging_belt: wgpu::util::StagingBelt,
     on_error_cb: ErrorCallback,
     on_success_cb: SuccessCallback,
+    on_timing_cb: TimingCallback,
     channels: [wgpu::Texture; 2],
+    channel_descs: [ChannelDesc; 2],
+    geometry: GeometryBuffers,
+    storage_buffers: [wgpu::Buffer; 2],
+    sound_buffer: wgpu::Buffer,
+    // Uniform mirror of `sound_sample_counter`, so dispatches concatenate.
+    sound_base_sample_buffer: wgpu::Buffer,
+    sound_sample_counter: u32,
+    // Pipeline plus its `@workgroup_size(x, ..)` x-dimension.
+    sound_pipeline: Option<(wgpu::ComputePipeline, u32)>,
+    on_audio_cb: AudioCallback,
+    includes: std::collections::HashMap<String, String>,
     pass_f32: bool,
     screen_blitter: blit::Blitter,
     query_set: Option<wgpu::QuerySet>,
+    // Smoothed per-entry-point GPU time, shared with the async readback task.
+    timing_ema: std::rc::Rc<std::cell::RefCell<std::collections::HashMap<String, f64>>>,
+}
+
+// Read-only storage holding an uploaded triangle mesh's interleaved vertex
+// data, plus a tiny uniform recording how many indices it describes.
+struct GeometryBuffers {
+    vertices: wgpu::Buffer,
+    index_count: wgpu::Buffer,
+}
+
+fn create_geometry_buffers(wgpu: &WgpuContext) -> GeometryBuffers {
+    let vertices = wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size_of::<[f32; 4]>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let index_count = wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size_of::<u32>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    wgpu.queue.write_buffer(&index_count, 0, bytemuck::bytes_of(&0u32));
+    GeometryBuffers { vertices, index_count }
+}
+
+// Placeholder for one of the two generic `dataN` storage bindings;
+// `set_storage_buffer` reallocates this when an upload is larger than it.
+fn create_storage_buffer(wgpu: &WgpuContext) -> wgpu::Buffer {
+    wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size_of::<[f32; 4]>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
 }
 
 static SHADER_ERROR: AtomicBool = AtomicBool::new(false);
 
-fn compute_bind_group_layout_entries(pass_f32: bool) -> [wgpu::BindGroupLayoutEntry; 14] {
+// Describes how a channel texture is exposed to the bind group layout; loaders
+// for non-LDR formats pick the matching sample_type/view_dimension.
+#[derive(Clone, Copy)]
+struct ChannelDesc {
+    view_dimension: wgpu::TextureViewDimension,
+    sample_type: wgpu::TextureSampleType,
+}
+
+impl Default for ChannelDesc {
+    fn default() -> Self {
+        ChannelDesc {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        }
+    }
+}
+
+fn compute_bind_group_layout_entries(pass_f32: bool, channel_descs: &[ChannelDesc; 2]) -> [wgpu::BindGroupLayoutEntry; 20] {
     [
         wgpu::BindGroupLayoutEntry {
             binding: 0,
@@ -223,8 +590,8 @@ fn compute_bind_group_layout_entries(pass_f32: bool) -> [wgpu::BindGroupLayoutEn
             visibility: wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::Texture {
                 multisampled: false,
-                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: channel_descs[0].sample_type,
+                view_dimension: channel_descs[0].view_dimension,
             },
             count: None,
         },
@@ -233,8 +600,8 @@ fn compute_bind_group_layout_entries(pass_f32: bool) -> [wgpu::BindGroupLayoutEn
             visibility: wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::Texture {
                 multisampled: false,
-                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: channel_descs[1].sample_type,
+                view_dimension: channel_descs[1].view_dimension,
             },
             count: None,
         },
@@ -256,6 +623,66 @@ fn compute_bind_group_layout_entries(pass_f32: bool) -> [wgpu::BindGroupLayoutEn
             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
             count: None,
         },
+        wgpu::BindGroupLayoutEntry {
+            binding: 9,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 12,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 13,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 14,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 15,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 16,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
     ]
 }
 
@@ -281,7 +708,7 @@ fn create_uniforms(wgpu: &WgpuContext, width: u32, height: u32, pass_f32: bool)
         }),
         custom: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: (MAX_CUSTOM_PARAMS * size_of::<f32>()) as u64,
+            size: MAX_CUSTOM_BYTES as u64,
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
             mapped_at_creation: false,
         }),
@@ -339,7 +766,7 @@ fn create_uniforms(wgpu: &WgpuContext, width: u32, height: u32, pass_f32: bool)
     }
 }
 
-fn create_compute_bind_group(wgpu: &WgpuContext, layout: &wgpu::BindGroupLayout, uniforms: &Uniforms, channels: &[wgpu::Texture]) -> wgpu::BindGroup {
+fn create_compute_bind_group(wgpu: &WgpuContext, layout: &wgpu::BindGroupLayout, uniforms: &Uniforms, channels: &[wgpu::Texture], geometry: &GeometryBuffers, storage_buffers: &[wgpu::Buffer; 2], sound_buffer: &wgpu::Buffer, sound_base_sample: &wgpu::Buffer) -> wgpu::BindGroup {
     wgpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
         layout,
@@ -359,6 +786,12 @@ fn create_compute_bind_group(wgpu: &WgpuContext, layout: &wgpu::BindGroupLayout,
                 ..Default::default()
             })) },
             wgpu::BindGroupEntry { binding: 8, resource: uniforms.debug_buffer.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 9, resource: geometry.vertices.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 12, resource: geometry.index_count.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 13, resource: storage_buffers[0].as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 14, resource: storage_buffers[1].as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 15, resource: sound_buffer.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 16, resource: sound_base_sample.as_entire_binding() },
             wgpu::BindGroupEntry { binding: 10, resource: wgpu::BindingResource::TextureView(&channels[0].create_view(&Default::default())) },
             wgpu::BindGroupEntry { binding: 11, resource: wgpu::BindingResource::TextureView(&channels[1].create_view(&Default::default())) },
             wgpu::BindGroupEntry { binding: 20, resource: wgpu::BindingResource::Sampler(&wgpu.device.create_sampler(&Default::default())) },
@@ -390,15 +823,60 @@ fn count_newlines(s: &str) -> usize {
     s.as_bytes().iter().filter(|&&c| c == b'\n').count()
 }
 
+// For each pass, which layers it writes need copying from tex_write into
+// tex_read before the next read. `render_to` loops this pass list forever, so
+// walking it twice (`0..2*n`) lets a read early in the list correctly see the
+// write that wraps around from the end of the previous frame.
+fn schedule_tex_copies(passes: &[ComputePass]) -> Vec<u8> {
+    let n = passes.len();
+    let mut copy_masks = vec![0u8; n];
+    for layer in 0..NUM_TEX_LAYERS {
+        let bit = 1 << layer;
+        let mut last_write: Option<usize> = None;
+        for k in 0..2 * n {
+            let pass = &passes[k % n];
+            if pass.layers_read & bit != 0 {
+                if let Some(w) = last_write {
+                    copy_masks[w] |= bit;
+                }
+            }
+            if pass.layers_write & bit != 0 {
+                last_write = Some(k % n);
+            }
+        }
+    }
+    copy_masks
+}
+
+// Splits a layer bitmask into contiguous (start_layer, count) runs for
+// single `copy_texture_to_texture` calls.
+fn layer_runs(mask: u8) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut layer = 0;
+    while layer < NUM_TEX_LAYERS {
+        if mask & (1 << layer) != 0 {
+            let start = layer;
+            while layer < NUM_TEX_LAYERS && mask & (1 << layer) != 0 {
+                layer += 1;
+            }
+            runs.push((start, layer - start));
+        } else {
+            layer += 1;
+        }
+    }
+    runs
+}
+
 #[wasm_bindgen]
 impl WgpuToyRenderer {
     #[wasm_bindgen(constructor)]
     pub fn new(wgpu: WgpuContext) -> WgpuToyRenderer {
         let size = wgpu.window.inner_size();
         let uniforms = create_uniforms(&wgpu, size.width, size.height, false);
+        let channel_descs = [ChannelDesc::default(), ChannelDesc::default()];
         let compute_bind_group_layout = wgpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &compute_bind_group_layout_entries(false),
+            entries: &compute_bind_group_layout_entries(false, &channel_descs),
         });
 
         let blank = wgpu::TextureDescriptor {
@@ -418,6 +896,20 @@ impl WgpuToyRenderer {
             wgpu.device.create_texture(&blank),
             wgpu.device.create_texture(&blank),
         ];
+        let geometry = create_geometry_buffers(&wgpu);
+        let storage_buffers = [create_storage_buffer(&wgpu), create_storage_buffer(&wgpu)];
+        let sound_buffer = wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SOUND_SAMPLES_PER_DISPATCH as u64) * size_of::<[f32; 2]>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let sound_base_sample_buffer = wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
         WgpuToyRenderer {
             compute_pipeline_layout: wgpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -425,7 +917,7 @@ impl WgpuToyRenderer {
                 bind_group_layouts: &[&compute_bind_group_layout],
                 push_constant_ranges: &[],
             }),
-            compute_bind_group: create_compute_bind_group(&wgpu, &compute_bind_group_layout, &uniforms, &channels),
+            compute_bind_group: create_compute_bind_group(&wgpu, &compute_bind_group_layout, &uniforms, &channels, &geometry, &storage_buffers, &sound_buffer, &sound_base_sample_buffer),
             last_compute_pipelines: None,
             compute_pipelines: vec![],
             screen_width: size.width,
@@ -451,11 +943,22 @@ impl WgpuToyRenderer {
             compute_bind_group_layout,
             on_error_cb: ErrorCallback(None),
             on_success_cb: SuccessCallback(None),
+            on_timing_cb: TimingCallback(None),
             channels,
-            custom_names: vec!["_dummy".into()], // just to avoid creating an empty struct in wgsl
-            custom_values: vec![0.],
+            channel_descs,
+            geometry,
+            storage_buffers,
+            sound_buffer,
+            sound_base_sample_buffer,
+            sound_sample_counter: 0,
+            sound_pipeline: None,
+            on_audio_cb: AudioCallback(None),
+            includes: std::collections::HashMap::new(),
+            // just to avoid creating an empty struct in wgsl
+            custom_params: vec![CustomParam { name: "_dummy".into(), ty: CustomType::Float, values: vec![0.] }],
             pass_f32: false,
             query_set: None,
+            timing_ema: std::rc::Rc::new(std::cell::RefCell::new(std::collections::HashMap::new())),
         }
     }
 
@@ -468,7 +971,7 @@ impl WgpuToyRenderer {
 
     fn render_to(&mut self, frame: wgpu::SurfaceTexture) {
         let mut encoder = self.wgpu.device.create_command_encoder(&Default::default());
-        let custom_bytes: Vec<u8> = self.custom_values.iter().flat_map(|x| bytemuck::bytes_of(x).iter().copied()).collect();
+        let custom_bytes: Vec<u8> = pack_custom(&self.custom_params);
         stage(&mut self.staging_belt, &self.wgpu.device, &mut encoder, &custom_bytes, &self.uniforms.custom);
         stage(&mut self.staging_belt, &self.wgpu.device, &mut encoder, bytemuck::bytes_of(&self.time), &self.uniforms.time);
         stage(&mut self.staging_belt, &self.wgpu.device, &mut encoder, bytemuck::bytes_of(&self.mouse), &self.uniforms.mouse);
@@ -484,36 +987,57 @@ impl WgpuToyRenderer {
                 }
             }
         }
-        for (pass_index, (pipeline, workgroup_size)) in self.compute_pipelines.iter().enumerate() {
+        let copy_masks = schedule_tex_copies(&self.compute_pipelines);
+        for (pass_index, pass) in self.compute_pipelines.iter().enumerate() {
             let mut compute_pass = encoder.begin_compute_pass(&Default::default());
             if let Some(q) = &self.query_set {
                 compute_pass.write_timestamp(q, 2 * pass_index as u32);
             }
-            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_pipeline(&pass.pipeline);
             compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
-            compute_pass.dispatch(self.screen_width.div_ceil(&workgroup_size[0]), self.screen_height.div_ceil(&workgroup_size[1]), 1);
+            compute_pass.dispatch(self.screen_width.div_ceil(&pass.workgroup_size[0]), self.screen_height.div_ceil(&pass.workgroup_size[1]), 1);
             if let Some(q) = &self.query_set {
                 compute_pass.write_timestamp(q, 2 * pass_index as u32 + 1);
             }
             drop(compute_pass);
-            encoder.copy_texture_to_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &self.uniforms.tex_write,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::ImageCopyTexture {
-                    texture: &self.uniforms.tex_read,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::Extent3d {
-                    width: self.screen_width,
-                    height: self.screen_height,
-                    depth_or_array_layers: 4,
-                });
+            for (layer, count) in layer_runs(copy_masks[pass_index]) {
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.uniforms.tex_write,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &self.uniforms.tex_read,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: self.screen_width,
+                        height: self.screen_height,
+                        depth_or_array_layers: count as u32,
+                    });
+            }
+        }
+        let mut sound_staging_buffer = None;
+        if let Some((pipeline, workgroup_size_x)) = &self.sound_pipeline {
+            self.wgpu.queue.write_buffer(&self.sound_base_sample_buffer, 0, bytemuck::bytes_of(&self.sound_sample_counter));
+            let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            compute_pass.dispatch(SOUND_SAMPLES_PER_DISPATCH.div_ceil(workgroup_size_x), 1, 1);
+            drop(compute_pass);
+            self.sound_sample_counter = self.sound_sample_counter.wrapping_add(SOUND_SAMPLES_PER_DISPATCH);
+            let buf = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: self.sound_buffer.size(),
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            encoder.copy_buffer_to_buffer(&self.sound_buffer, 0, &buf, 0, self.sound_buffer.size());
+            sound_staging_buffer = Some(buf);
         }
         let mut staging_buffer = None;
         let query_offset = NUM_ASSERT_COUNTERS * size_of::<u32>();
@@ -537,6 +1061,11 @@ impl WgpuToyRenderer {
         if let Some(buf) = staging_buffer {
             self.wgpu.device.poll(wgpu::Maintain::Wait);
             let numthreads = self.screen_width * self.screen_height;
+            let timestamp_period = self.wgpu.queue.get_timestamp_period();
+            let pass_names: Vec<String> = self.compute_pipelines.iter().map(|p| p.name.clone()).collect();
+            let have_timestamps = self.query_set.is_some();
+            let on_timing_cb = self.on_timing_cb.clone();
+            let timing_ema = self.timing_ema.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 let buffer_slice = buf.slice(..);
                 match buffer_slice.map_async(wgpu::MapMode::Read).await {
@@ -551,6 +1080,38 @@ impl WgpuToyRenderer {
                                 log::warn!("Assertion {i} failed in {percent}% of threads");
                             }
                         }
+                        if have_timestamps {
+                            let mut ema = timing_ema.borrow_mut();
+                            let timings: Vec<(String, f64)> = pass_names.iter().enumerate().filter_map(|(i, name)| {
+                                let (start, end) = (timestamps.get(2 * i)?, timestamps.get(2 * i + 1)?);
+                                let ns = end.saturating_sub(*start) as f64 * timestamp_period as f64;
+                                let ms = ns / 1_000_000.;
+                                let smoothed = match ema.get(name) {
+                                    Some(prev) => prev + TIMING_EMA_ALPHA * (ms - prev),
+                                    None => ms,
+                                };
+                                ema.insert(name.clone(), smoothed);
+                                Some((name.clone(), smoothed))
+                            }).collect();
+                            drop(ema);
+                            on_timing_cb.call(&timings);
+                        }
+                    }
+                }
+                buf.unmap();
+            });
+        }
+        if let Some(buf) = sound_staging_buffer {
+            self.wgpu.device.poll(wgpu::Maintain::Wait);
+            let on_audio_cb = self.on_audio_cb.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let buffer_slice = buf.slice(..);
+                match buffer_slice.map_async(wgpu::MapMode::Read).await {
+                    Err(e) => log::error!("{e}"),
+                    Ok(()) => {
+                        let data = buffer_slice.get_mapped_range();
+                        let samples: &[f32] = bytemuck::cast_slice(&data);
+                        on_audio_cb.call(samples, SOUND_SAMPLE_RATE);
                     }
                 }
                 buf.unmap();
@@ -573,9 +1134,11 @@ impl WgpuToyRenderer {
             struct Mouse { pos: uint2, click: int };
         "#);
         s.push_str("struct Custom {");
-        for name in &self.custom_names {
-            s.push_str(name);
-            s.push_str(": float,");
+        for p in &self.custom_params {
+            s.push_str(&p.name);
+            s.push_str(": ");
+            s.push_str(p.ty.wgsl_name());
+            s.push(',');
         }
         s.push_str("};");
         s.push_str("@group(0) @binding(0) var<uniform> custom: Custom;");
@@ -589,6 +1152,12 @@ impl WgpuToyRenderer {
             @group(0) @binding(6) var pass_in: texture_2d_array<f32>;
             @group(0) @binding(7) var pass_out: texture_storage_2d_array<{pass_format},write>;
             @group(0) @binding(8) var<storage,read_write> _assert_counts: array<atomic<u32>>;
+            @group(0) @binding(9) var<storage,read> geometry: array<vec4<f32>>;
+            @group(0) @binding(12) var<uniform> geometry_index_count: uint;
+            @group(0) @binding(13) var<storage,read> data0: array<vec4<f32>>;
+            @group(0) @binding(14) var<storage,read> data1: array<vec4<f32>>;
+            @group(0) @binding(15) var<storage,read_write> sound_out: array<vec2<f32>>;
+            @group(0) @binding(16) var<uniform> sound_base_sample: uint;
             @group(0) @binding(10) var channel0: texture_2d<f32>;
             @group(0) @binding(11) var channel1: texture_2d<f32>;
             @group(0) @binding(20) var nearest: sampler;
@@ -604,43 +1173,93 @@ impl WgpuToyRenderer {
                     atomicAdd(&_assert_counts[index], 1u);
                 }
             }
+            fn sampleChannel0(uv: float2, lod: float) -> float4 {
+                return textureSampleLevel(channel0, trilinear, uv, lod);
+            }
+            fn sampleChannel1(uv: float2, lod: float) -> float4 {
+                return textureSampleLevel(channel1, trilinear, uv, lod);
+            }
         "#);
         return s;
     }
 
-    fn handle_error(&self, e: ParseError, wgsl: &str) {
+    // `line_map[i]` maps the 1-based flattened row `prelude_len + i + 1` back
+    // to the virtual file/line an `#include` expanded from.
+    fn handle_error(&self, e: ParseError, wgsl: &str, line_map: &[(String, usize)]) {
         let prelude_len = count_newlines(&self.prelude()); // in case we need to report errors
         let (row, col) = e.location(&wgsl);
         let summary = e.emit_to_string(&wgsl);
-        self.on_error_cb.call(&summary, if row >= prelude_len { row - prelude_len } else { 0 }, col);
+        if row < prelude_len {
+            self.on_error_cb.call(&summary, row, col, "<prelude>");
+        } else {
+            match line_map.get(row - prelude_len - 1) {
+                Some((file, orig_line)) => self.on_error_cb.call(&summary, *orig_line, col, file),
+                None => self.on_error_cb.call(&summary, row - prelude_len, col, "<shader>"),
+            }
+        }
     }
 
     fn handle_success(&self, entry_points: Vec<String>) {
         self.on_success_cb.call(entry_points);
     }
 
+    // Registers a virtual file `#include "name"` can pull in. Doesn't itself
+    // trigger recompilation; call `set_shader` again afterwards.
+    pub fn set_include(&mut self, name: &str, source: &str) {
+        self.includes.insert(name.to_string(), source.to_string());
+    }
+
     pub fn set_shader(&mut self, shader: &str) {
+        let preprocessed = match preprocess(shader, &self.includes) {
+            Ok(p) => p,
+            Err(e) => {
+                log::error!("Error preprocessing WGSL: {e}");
+                self.on_error_cb.call(&e, 0, 0, "<shader>");
+                return;
+            }
+        };
         let mut wgsl: String = self.prelude();
-        let shader: String = shader.into();
-        wgsl.push_str(&shader);
+        wgsl.push_str(&preprocessed.source);
         match wgsl::parse_str(&wgsl) {
             Ok(module) => {
-                let entry_points: Vec<_> = module.entry_points.iter()
+                let all_entry_points: Vec<_> = module.entry_points.iter()
                     .filter(|f| f.stage == naga::ShaderStage::Compute).collect();
-                let entry_point_names: Vec<String> = entry_points.iter().map(|entry_point| {entry_point.name.clone()}).collect();
+                let entry_point_names: Vec<String> = all_entry_points.iter().map(|entry_point| {entry_point.name.clone()}).collect();
                 self.handle_success(entry_point_names);
                 let compute_shader = self.wgpu.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
                     label: None,
                     source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(&wgsl)),
                 });
-                self.last_compute_pipelines = Some(take(&mut self.compute_pipelines));
-                self.compute_pipelines = entry_points.iter().map(|entry_point| {
-                    (self.wgpu.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                // `sound`, if present, is dispatched separately in `render_to`
+                // over sample indices, so it's excluded from compute_pipelines.
+                let (sound_entry, entry_points): (Vec<_>, Vec<_>) = all_entry_points.into_iter()
+                    .partition(|entry_point| entry_point.name == "sound");
+                self.sound_pipeline = sound_entry.first().map(|entry_point| {
+                    let pipeline = self.wgpu.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                         label: None,
                         layout: Some(&self.compute_pipeline_layout),
                         module: &compute_shader,
                         entry_point: &entry_point.name,
-                    }), entry_point.workgroup_size)
+                    });
+                    (pipeline, entry_point.workgroup_size[0])
+                });
+                let pass_layers = parse_pass_layers(&preprocessed.source);
+                self.last_compute_pipelines = Some(take(&mut self.compute_pipelines));
+                self.compute_pipelines = entry_points.iter().map(|entry_point| {
+                    let (layers_read, layers_write) = pass_layers.get(&entry_point.name)
+                        .copied().unwrap_or((ALL_LAYERS, ALL_LAYERS));
+                    ComputePass {
+                        pipeline: self.wgpu.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                            label: None,
+                            layout: Some(&self.compute_pipeline_layout),
+                            module: &compute_shader,
+                            entry_point: &entry_point.name,
+                        }),
+                        name: entry_point.name.clone(),
+                        workgroup_size: entry_point.workgroup_size,
+                        layers_read,
+                        layers_write,
+                    }
                 }).collect();
                 self.query_set = if !self.wgpu.device.features().contains(wgpu::Features::TIMESTAMP_QUERY) { None } else {
                     Some(self.wgpu.device.create_query_set(&wgpu::QuerySetDescriptor {
@@ -648,11 +1267,12 @@ impl WgpuToyRenderer {
                         count: 2 * self.compute_pipelines.len() as u32,
                         ty: wgpu::QueryType::Timestamp,
                     }))
-                }
+                };
+                self.timing_ema.borrow_mut().clear();
             },
             Err(e) => {
                 log::error!("Error parsing WGSL: {e}");
-                self.handle_error(e, &wgsl);
+                self.handle_error(e, &wgsl, &preprocessed.line_map);
             },
         }
     }
@@ -673,9 +1293,32 @@ impl WgpuToyRenderer {
         self.keys.set(keycode, keydown);
     }
 
+    // `types` holds one tag per name (see `CustomType::from_tag`); `values` is
+    // the flattened component list, e.g. a `Vec3` param contributes 3 entries.
+    pub fn set_custom_params(&mut self, names: Vec<js_sys::JsString>, types: Vec<u32>, values: Vec<f32>) {
+        let mut params = Vec::new();
+        let mut cursor = 0;
+        for (name, tag) in names.iter().zip(types.iter()) {
+            let ty = CustomType::from_tag(*tag);
+            let n = ty.components();
+            if cursor + n > values.len() {
+                log::error!("set_custom_params: values has {} entries, not enough for the types given", values.len());
+                return;
+            }
+            params.push(CustomParam { name: name.into(), ty, values: values[cursor..cursor + n].to_vec() });
+            cursor += n;
+        }
+        if params.is_empty() {
+            params.push(CustomParam { name: "_dummy".into(), ty: CustomType::Float, values: vec![0.] });
+        }
+        self.custom_params = params;
+    }
+
+    // Pre-typed-custom-input API, kept for older pages: every name gets
+    // exactly one `Float` value, mirroring the original all-floats scheme.
     pub fn set_custom_floats(&mut self, names: Vec<js_sys::JsString>, values: Vec<f32>) {
-        self.custom_names = names.iter().map(From::from).collect();
-        self.custom_values = values;
+        let types = vec![0u32; names.len()];
+        self.set_custom_params(names, types, values);
     }
 
     pub fn set_pass_f32(&mut self, pass_f32: bool) {
@@ -689,14 +1332,14 @@ impl WgpuToyRenderer {
         self.uniforms = create_uniforms(&self.wgpu, width, height, self.pass_f32);
         self.compute_bind_group_layout = self.wgpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &compute_bind_group_layout_entries(self.pass_f32),
+            entries: &compute_bind_group_layout_entries(self.pass_f32, &self.channel_descs),
         });
         self.compute_pipeline_layout = self.wgpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[&self.compute_bind_group_layout],
             push_constant_ranges: &[],
         });
-        self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels);
+        self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels, &self.geometry, &self.storage_buffers, &self.sound_buffer, &self.sound_base_sample_buffer);
         self.screen_blitter = blit::Blitter::new(
             &self.wgpu,
             &self.uniforms.tex_screen.create_view(&Default::default()),
@@ -721,7 +1364,7 @@ impl WgpuToyRenderer {
                     let row = cap[1].parse().unwrap_or(prelude_len);
                     let col = cap[2].parse().unwrap_or(0);
                     let summary = &cap[3];
-                    on_error_cb.call(summary, if row >= prelude_len { row - prelude_len } else { 0 }, col);
+                    on_error_cb.call(summary, if row >= prelude_len { row - prelude_len } else { 0 }, col, "<shader>");
                     SHADER_ERROR.store(true, Ordering::SeqCst);
                 }
             }
@@ -732,6 +1375,31 @@ impl WgpuToyRenderer {
         self.on_success_cb = SuccessCallback(Some(callback));
     }
 
+    pub fn on_timing(&mut self, callback: js_sys::Function) {
+        self.on_timing_cb = TimingCallback(Some(callback));
+    }
+
+    // Called once per frame with the samples produced by a `sound` entry point.
+    pub fn on_audio(&mut self, callback: js_sys::Function) {
+        self.on_audio_cb = AudioCallback(Some(callback));
+    }
+
+    // Rebuilds the compute bind group layout/pipeline layout/bind group from
+    // the current `channel_descs`. Like `resize`, doesn't recompile
+    // `compute_pipelines` — callers must re-submit via `set_shader` after.
+    fn rebuild_compute_layout(&mut self) {
+        self.compute_bind_group_layout = self.wgpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &compute_bind_group_layout_entries(self.pass_f32, &self.channel_descs),
+        });
+        self.compute_pipeline_layout = self.wgpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&self.compute_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels, &self.geometry, &self.storage_buffers, &self.sound_buffer, &self.sound_base_sample_buffer);
+    }
+
     pub fn load_channel(&mut self, index: usize, bytes: &[u8]) {
         match image::load_from_memory(bytes) {
             Err(e) => log::error!("load_channel: {e}"),
@@ -745,7 +1413,8 @@ impl WgpuToyRenderer {
                     wgpu::TextureFormat::Rgba8UnormSrgb,
                     wgpu::FilterMode::Linear,
                 ).create_texture(&self.wgpu, width, height, 1 + (std::cmp::max(width, height) as f32).log2() as u32);
-                self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels);
+                self.channel_descs[index] = ChannelDesc::default();
+                self.rebuild_compute_layout();
             }
         }
     }
@@ -762,10 +1431,170 @@ impl WgpuToyRenderer {
             wgpu::TextureFormat::Rgba16Float,
             wgpu::FilterMode::Linear,
         ).create_texture(&self.wgpu, meta.width, meta.height, 1 + (std::cmp::max(meta.width, meta.height) as f32).log2() as u32);
-        self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels);
+        self.channel_descs[index] = ChannelDesc::default();
+        self.rebuild_compute_layout();
         Ok(())
     }
 
+    // Loads a KTX2 container directly so compressed/HDR formats reach the GPU
+    // as-is; picks the matching sample_type/view_dimension and rebuilds the layout.
+    pub fn load_channel_ktx2(&mut self, index: usize, bytes: &[u8]) -> Result<(), String> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| e.to_string())?;
+        let header = reader.header();
+        let (format, required_feature) = ktx2_wgpu_format(header.format)
+            .ok_or_else(|| format!("unsupported KTX2 format: {:?}", header.format))?;
+        if let Some(feature) = required_feature {
+            if !self.wgpu.device.features().contains(feature) {
+                return Err(format!("device is missing {feature:?}, required to load this texture"));
+            }
+        }
+        let width = header.pixel_width;
+        let height = header.pixel_height.max(1);
+        let levels = header.level_count.max(1);
+        // Block-compressed containers get every level uploaded verbatim; a
+        // single-level float container gets its mip chain generated instead.
+        let texture = if levels > 1 || required_feature.is_some() {
+            let texture = self.wgpu.device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: levels,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            });
+            for (level, data) in reader.levels().enumerate() {
+                let mip_width = (width >> level).max(1);
+                let mip_height = (height >> level).max(1);
+                self.wgpu.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: level as u32,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    data,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(block_aligned_row_bytes(format, mip_width)),
+                        rows_per_image: std::num::NonZeroU32::new(mip_height),
+                    },
+                    wgpu::Extent3d { width: mip_width, height: mip_height, depth_or_array_layers: 1 },
+                );
+            }
+            texture
+        } else {
+            let level0 = reader.levels().next().ok_or("KTX2 container has no mip levels")?;
+            blit::Blitter::new(
+                &self.wgpu,
+                &create_texture_from_image(&self.wgpu, level0, width, height, format).create_view(&Default::default()),
+                blit::ColourSpace::Linear,
+                format,
+                wgpu::FilterMode::Linear,
+            ).create_texture(&self.wgpu, width, height, 1 + (std::cmp::max(width, height) as f32).log2() as u32)
+        };
+        self.channels[index] = texture;
+        self.channel_descs[index] = ChannelDesc {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: format != wgpu::TextureFormat::Rgba32Float },
+        };
+        self.rebuild_compute_layout();
+        Ok(())
+    }
+
+    // `vertices` is an interleaved `vec4<f32>` array; `index_count` is surfaced
+    // to the shader as `geometry_index_count`. Reallocates on growth.
+    pub fn set_geometry(&mut self, vertices: &[u8], index_count: u32) {
+        if vertices.len() as u64 > self.geometry.vertices.size() {
+            self.geometry.vertices = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: vertices.len() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        self.wgpu.queue.write_buffer(&self.geometry.vertices, 0, vertices);
+        self.wgpu.queue.write_buffer(&self.geometry.index_count, 0, bytemuck::bytes_of(&index_count));
+        self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels, &self.geometry, &self.storage_buffers, &self.sound_buffer, &self.sound_base_sample_buffer);
+    }
+
+    // Cheap per-frame path for live sources (webcam, decoded video) when
+    // `width`/`height` match the existing channel texture; logs and bails on
+    // a size mismatch instead of resizing — callers should use `load_channel`.
+    pub fn update_channel(&mut self, index: usize, rgba: &[u8], width: u32, height: u32) {
+        let current = self.channels[index].size();
+        if current.width != width || current.height != height {
+            log::error!(
+                "update_channel: {width}x{height} doesn't match existing channel{index} texture \
+                 ({}x{}); call load_channel to resize it first",
+                current.width, current.height
+            );
+            return;
+        }
+        let format = self.channels[index].format();
+        self.wgpu.queue.write_texture(
+            self.channels[index].as_image_copy(),
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(block_aligned_row_bytes(format, width)),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        if self.channels[index].mip_level_count() > 1 {
+            let base_view = self.channels[index].create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: 0,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            blit::Blitter::new(&self.wgpu, &base_view, blit::ColourSpace::Linear, format, wgpu::FilterMode::Linear)
+                .blit_mips_into(&self.wgpu, &self.channels[index]);
+        }
+    }
+
+    // Uploads arbitrary read-only data into `data0`/`data1` as `array<vec4<f32>>`.
+    pub fn set_storage_buffer(&mut self, index: usize, bytes: &[u8]) {
+        if bytes.len() as u64 > self.storage_buffers[index].size() {
+            self.storage_buffers[index] = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: bytes.len() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.compute_bind_group = create_compute_bind_group(&self.wgpu, &self.compute_bind_group_layout, &self.uniforms, &self.channels, &self.geometry, &self.storage_buffers, &self.sound_buffer, &self.sound_base_sample_buffer);
+        }
+        self.wgpu.queue.write_buffer(&self.storage_buffers[index], 0, bytes);
+    }
+
+}
+
+// Maps the KTX2/Vulkan formats we accept to a `wgpu::TextureFormat` plus the
+// device feature (if any) required to use it.
+fn ktx2_wgpu_format(format: Option<ktx2::Format>) -> Option<(wgpu::TextureFormat, Option<wgpu::Features>)> {
+    use ktx2::Format;
+    match format? {
+        Format::R16G16B16A16_SFLOAT => Some((wgpu::TextureFormat::Rgba16Float, None)),
+        Format::R32G32B32A32_SFLOAT => Some((wgpu::TextureFormat::Rgba32Float, None)),
+        Format::BC7_UNORM_BLOCK => Some((wgpu::TextureFormat::Bc7RgbaUnorm, Some(wgpu::Features::TEXTURE_COMPRESSION_BC))),
+        Format::BC7_SRGB_BLOCK => Some((wgpu::TextureFormat::Bc7RgbaUnormSrgb, Some(wgpu::Features::TEXTURE_COMPRESSION_BC))),
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => Some((wgpu::TextureFormat::Etc2Rgba8Unorm, Some(wgpu::Features::TEXTURE_COMPRESSION_ETC2))),
+        Format::ASTC_4X4_UNORM_BLOCK => Some((wgpu::TextureFormat::Astc4x4RgbaUnorm, Some(wgpu::Features::TEXTURE_COMPRESSION_ASTC))),
+        _ => None,
+    }
+}
+
+// `bytes_per_row` for a mip level of the given format/width: block-compressed
+// formats are laid out in 4x4 blocks, so the row pitch is in blocks-of-width
+// rather than pixels-of-width.
+fn block_aligned_row_bytes(format: wgpu::TextureFormat, width: u32) -> u32 {
+    match format {
+        wgpu::TextureFormat::Bc7RgbaUnorm | wgpu::TextureFormat::Bc7RgbaUnormSrgb
+        | wgpu::TextureFormat::Etc2Rgba8Unorm | wgpu::TextureFormat::Astc4x4RgbaUnorm => width.div_ceil(&4) * 16,
+        wgpu::TextureFormat::Rgba16Float => width * 8,
+        wgpu::TextureFormat::Rgba32Float => width * 16,
+        _ => width * 4,
+    }
 }
 
 fn create_texture_from_image(wgpu: &WgpuContext, rgba: &[u8], width: u32, height: u32, format: wgpu::TextureFormat) -> wgpu::Texture {
@@ -789,7 +1618,7 @@ fn create_texture_from_image(wgpu: &WgpuContext, rgba: &[u8], width: u32, height
         rgba,
         wgpu::ImageDataLayout {
             offset: 0,
-            bytes_per_row: std::num::NonZeroU32::new(4 * width),
+            bytes_per_row: std::num::NonZeroU32::new(block_aligned_row_bytes(format, width)),
             rows_per_image: std::num::NonZeroU32::new(height),
         },
         wgpu::Extent3d {
@@ -799,4 +1628,34 @@ fn create_texture_from_image(wgpu: &WgpuContext, rgba: &[u8], width: u32, height
         },
     );
     texture
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regression for the `handle_error` line_map lookup: `line_map[i]` maps to
+    // flattened (1-based) line `i+1`, so a row at `prelude_len + k` must index
+    // `line_map[k - 1]`, not `line_map[k]`. Exercises an #include boundary so a
+    // wrong index would also attribute the error to the wrong file.
+    #[test]
+    fn handle_error_line_lookup_matches_source_across_include() {
+        let mut includes = std::collections::HashMap::new();
+        includes.insert("foo".to_string(), "x\ny\n".to_string());
+        let preprocessed = preprocess("a\n#include \"foo\"\nc\n", &includes).unwrap();
+        assert_eq!(
+            preprocessed.line_map,
+            vec![
+                ("<shader>".to_string(), 1),
+                ("foo".to_string(), 1),
+                ("foo".to_string(), 2),
+                ("<shader>".to_string(), 3),
+            ]
+        );
+
+        let prelude_len = 5;
+        let row = prelude_len + 2; // 1-based row of flattened line 2 ("x" from foo:1)
+        let (file, orig_line) = preprocessed.line_map.get(row - prelude_len - 1).unwrap();
+        assert_eq!((file.as_str(), *orig_line), ("foo", 1));
+    }
+}